@@ -1,6 +1,23 @@
+import codecs
+import os
+import shutil
+
 import click
 
-from .code_format import code_format
+from .code_format import FormatError, code_format
+from .common import DecodeError, decode_source, encode_source, expand_leading_tabs
+
+
+class InputError(click.ClickException):
+    exit_code = 2
+
+
+class OutputError(click.ClickException):
+    exit_code = 2
+
+
+class InternalError(click.ClickException):
+    exit_code = 3
 
 
 @click.command()
@@ -8,18 +25,196 @@ from .code_format import code_format
     "input_file",
     default="-",
     envvar="RPYFMT_IN",
-    type=click.File("r", encoding="utf-8"),
+    type=click.File("rb"),
 )
 @click.argument(
     "output_file",
     default="-",
     envvar="RPYFMT_OUT",
-    type=click.File("w", encoding="utf-8"),
+    type=click.Path(dir_okay=False, allow_dash=True),
+)
+@click.option(
+    "--line-ending",
+    default="auto",
+    show_default=True,
+    type=click.Choice(["auto", "lf", "crlf"]),
+    help="Line ending of the output. 'auto' keeps the one used by the input.",
+)
+@click.option(
+    "--split-dollar-lines",
+    is_flag=True,
+    help="Give every statement of a `$` line with semicolons its own `$` line.",
+)
+@click.option(
+    "--check",
+    is_flag=True,
+    help="Don't write the output, exit with 1 if the input would be reformatted.",
+)
+@click.option(
+    "--backup",
+    is_flag=True,
+    help="Keep a `.bak` copy of an existing output file before changing it. An "
+    "existing backup is kept, so it holds the file as it was before the first run.",
+)
+@click.option(
+    "--backup-dir",
+    type=click.Path(file_okay=False),
+    help="Keep backups in this directory instead, at the same path relative to "
+    "the current directory. Implies --backup.",
+)
+@click.option(
+    "--fix-tabs",
+    is_flag=True,
+    help="Convert tabs in indentation to spaces. Ren'Py rejects tab indentation.",
+)
+@click.option(
+    "--tab-width",
+    default=4,
+    show_default=True,
+    type=click.IntRange(min=1),
+    help="Distance between tab stops used by --fix-tabs.",
+)
+@click.option(
+    "--encoding",
+    "fallback_encoding",
+    help="Encoding to read scripts with that are not UTF-8, e.g. cp932. "
+    "The output is always UTF-8.",
+)
+@click.option(
+    "--verify",
+    is_flag=True,
+    help="Check that the formatted Python code is equivalent to the original.",
 )
-def cli(input_file, output_file):
-    text = input_file.read()
-    text_fmt = code_format(text)
-    output_file.write(text_fmt)
+@click.option(
+    "--error-on",
+    default="errors",
+    show_default=True,
+    type=click.Choice(["warnings", "errors", "never"]),
+    help="What fails the run with exit code 2: warnings as well as errors, only "
+    "errors, or nothing. Input with errors is left unformatted.",
+)
+def cli(
+    input_file,
+    output_file,
+    line_ending,
+    split_dollar_lines,
+    check,
+    backup,
+    backup_dir,
+    fix_tabs,
+    tab_width,
+    fallback_encoding,
+    verify,
+    error_on,
+):
+    if fallback_encoding is not None:
+        try:
+            fallback_encoding = codecs.lookup(fallback_encoding).name
+        except LookupError:
+            raise click.BadParameter(
+                f"unknown encoding {fallback_encoding!r}", param_hint="'--encoding'"
+            )
+
+    backup_path = None
+    if (backup or backup_dir) and output_file != "-":
+        backup_path = get_backup_path(output_file, backup_dir)
+
+    try:
+        data = input_file.read()
+    except OSError as e:
+        raise InputError(f"{input_file.name}: cannot read input: {e.strerror or e}")
+    warnings = []
+
+    def warn(message):
+        click.echo(f"{input_file.name}: {message}", err=True)
+        warnings.append(message)
+
+    try:
+        try:
+            text, encoding, bom = decode_source(data, fallback_encoding)
+        except DecodeError as e:
+            raise InputError(f"{input_file.name}: {e}")
+        if encoding != "utf-8":
+            warn(f"read as {encoding}, output is transcoded to UTF-8")
+        source = text
+        if fix_tabs:
+            source, changed = expand_leading_tabs(text, tab_width)
+            if changed:
+                warn(f"converted tab indentation to spaces on {changed} line(s)")
+
+        try:
+            text_fmt = code_format(
+                source,
+                line_ending=line_ending,
+                split_dollar_lines=split_dollar_lines,
+                verify=verify,
+            )
+        except FormatError as e:
+            raise InputError(f"{input_file.name}:{e}")
+        except Exception as e:
+            raise InternalError(f"{input_file.name}: internal error: {e!r}")
+    except InputError as e:
+        if error_on != "never":
+            raise
+        # The input is passed through as it is, so that nothing is lost when
+        # formatting into another file or a pipe.
+        click.echo(f"Warning: {e.format_message()}", err=True)
+        if not check:
+            write_output(output_file, data, backup_path)
+        return
+
+    reformat = text_fmt != text or encoding != "utf-8"
+    if check and reformat:
+        click.echo(f"would reformat {input_file.name}", err=True)
+    elif not check:
+        write_output(output_file, encode_source(text_fmt, bom=bom), backup_path)
+
+    if warnings and error_on == "warnings":
+        raise SystemExit(InputError.exit_code)
+    if check and reformat:
+        raise SystemExit(1)
+
+
+def get_backup_path(output_file, backup_dir):
+    """Return where to back up `output_file`, in `backup_dir` if given."""
+    if backup_dir is None:
+        return f"{output_file}.bak"
+    path = os.path.abspath(output_file)
+    try:
+        relative = os.path.relpath(path)
+    except ValueError:  # On another drive.
+        relative = os.pardir
+    if relative.startswith(os.pardir):
+        # Files outside the current directory keep their full path.
+        relative = os.path.splitdrive(path)[1].lstrip(os.sep)
+    return os.path.join(backup_dir, relative)
+
+
+def write_output(output_file, data, backup_path):
+    try:
+        _write_output(output_file, data, backup_path)
+    except OSError as e:
+        raise OutputError(f"{output_file}: cannot write output: {e.strerror or e}")
+
+
+def _write_output(output_file, data, backup_path):
+    if output_file == "-":
+        click.get_binary_stream("stdout").write(data)
+        return
+
+    # Leave an existing output file alone, including its modification time and
+    # any backup, if formatting doesn't change it.
+    if os.path.exists(output_file):
+        with open(output_file, "rb") as f:
+            if f.read() == data:
+                return
+        # An existing backup holds the file from before an earlier run, which
+        # is the copy worth keeping.
+        if backup_path and not os.path.exists(backup_path):
+            os.makedirs(os.path.dirname(backup_path) or ".", exist_ok=True)
+            shutil.copy2(output_file, backup_path)
+    with open(output_file, "wb") as f:
+        f.write(data)
 
 
 if __name__ == "__main__":
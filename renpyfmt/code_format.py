@@ -1,89 +1,229 @@
+import ast
 import copy
 import re
-from collections import defaultdict
 
 import black
 
-from .common import dedent, indent
+from .common import (
+    LINE_ENDINGS,
+    continues_logical_line,
+    dedent,
+    detect_line_ending,
+    find_unbalanced,
+    indent,
+    split_lines,
+    string_lines,
+)
 
+_dollar_re = re.compile(r"(\s+)\$\s*(.*)")
+_dollar_prefix_re = re.compile(r"^\s*\$?\s*")
+_python_block_re = re.compile(
+    r"(\s*)("
+    r"(?:init(?:\s+[-+]?\d+)?\s+)?python(?:\s+early)?(?:\s+hide)?(?:\s+in\s+[\w.]+)?"
+    r")\s*:$"
+)
+_trailing_whitespace_re = re.compile(r"\s+$")
+_black_error_re = re.compile(r"Cannot parse: (\d+):(\d+): (.*)", re.DOTALL)
 
-def code_format(source):
-    source = [line.rstrip() for line in source.splitlines()]
+
+class FormatError(Exception):
+    """Raised when Python code embedded in a script cannot be parsed."""
+
+    def __init__(self, line, column, message):
+        super().__init__(f"{line}:{column}: cannot parse: {message}")
+        self.line = line
+        self.column = column
+        self.message = message
+
+
+def format_error(
+    e, code, first_line, last_line, column_offset, continuation_offset=None
+):
+    """Translate a black parse error for `code` into a `FormatError` in the script.
+
+    `first_line` is the 1-based script line of the first line of `code` and
+    `column_offset` the number of characters preceding `code` on each line.
+    For code that only starts partway into its first line, like a `$`
+    statement spanning several lines, `continuation_offset` gives the number
+    for the lines after the first. Errors reported past the end of the code
+    are pinned to `last_line`.
+    """
+    if continuation_offset is None:
+        continuation_offset = column_offset
+
+    def offset(line):
+        return column_offset if line == 1 else continuation_offset
+
+    def position(line, column):
+        return f"{first_line + line - 1}:{offset(line) + column}"
+
+    # black drops leading blank lines before parsing.
+    blank_lines = code[: len(code) - len(code.lstrip())].count("\n")
+    m = _black_error_re.match(str(e))
+    if m:
+        line, column, message = m.groups()
+        code_line = blank_lines + int(line)
+        line = min(first_line + code_line - 1, last_line)
+        column = offset(code_line) + int(column) + 1
+    else:
+        line, column, message = first_line, column_offset + 1, str(e)
+    if unbalanced := find_unbalanced(code, position):
+        message = f"{message} ({unbalanced})"
+    return FormatError(line, column, message)
+
+
+def format_dollar(code):
+    """Format the code of a `$` statement, returning one string per Python statement.
+
+    Returns None if the code contains a compound statement (`if`, `for`, ...),
+    since black would split it into an indented block that can no longer be
+    written as a single `$` line. Magic trailing commas are ignored for the
+    same reason.
+    """
+    code_fmt = black.format_str(
+        code, mode=black.Mode(line_length=1000, magic_trailing_comma=False)
+    )
+    lines = split_lines(code_fmt)
+    statements = []
+    for node in ast.parse(code_fmt).body:
+        if hasattr(node, "body"):
+            return None
+        statements.append("\n".join(lines[node.lineno - 1 : node.end_lineno]))
+    return statements
+
+
+def code_format(source, line_ending="auto", split_dollar_lines=False, verify=False):
+    if line_ending == "auto":
+        newline = detect_line_ending(source)
+        final_newline = source.endswith(("\n", "\r"))
+    else:
+        newline = LINE_ENDINGS[line_ending]
+        final_newline = True
+
+    source = split_lines(source)
+    strings = string_lines(source)
+    # Trailing whitespace before a line break inside a string is part of it.
+    source = [
+        line if line_num + 1 in strings else line.rstrip()
+        for line_num, line in enumerate(source)
+    ]
 
     reformatted = {}
 
-    for line_num, line in enumerate(source):
-        m = re.match(r"(\s+)\$\s*(.*)", line)
-        if not m:
+    continuation_lines = set()
+    line_num = 0
+    while line_num < len(source):
+        m = _dollar_re.match(source[line_num])
+        if not m or line_num in strings:
+            line_num += 1
             continue
         leading_spaces, code = m.groups()
-        src_indent = len(leading_spaces)
-        code_fmt = black.format_str(code, mode=black.Mode(line_length=1000)).split("\n")
-        new_code = ""
-        for i, line in enumerate(code_fmt):
-            spaces = " " * src_indent
-            if i == 0:
-                new_code += f"{spaces}$ {line}\n"
+
+        # Like any logical line, a `$` statement carries on over the following
+        # lines while a bracket or a multi-line string is open.
+        start = end = line_num
+        while continues_logical_line(code) and end + 1 < len(source):
+            end += 1
+            code += "\n" + source[end]
+        continuation_lines.update(range(start + 1, end + 1))
+        line_num = end + 1
+
+        try:
+            statements = format_dollar(code)
+        except black.InvalidInput as e:
+            raise format_error(e, code, start + 1, end + 1, m.start(2), 0)
+        if not statements:
+            continue
+        if split_dollar_lines:
+            code_fmt = "\n".join(f"$ {statement}" for statement in statements)
+        else:
+            code_fmt = "$ " + "; ".join(statements)
+        # Lines inside multi-line strings are part of the value, so they are not
+        # indented.
+        lines = code_fmt.split("\n")
+        fmt_strings = string_lines(lines)
+        new_code = "\n".join(
+            line if n in fmt_strings else f"{leading_spaces}{line}"
+            for n, line in enumerate(lines)
+        )
+        new_code = _trailing_whitespace_re.sub("", new_code)
+        if verify:
+            # Check the lines that end up in the script, not just black's output.
+            black.assert_equivalent(
+                code,
+                "\n".join(
+                    line if n in fmt_strings else _dollar_prefix_re.sub("", line)
+                    for n, line in enumerate(new_code.split("\n"))
+                ),
+            )
+        reformatted[(start, end)] = new_code
+
+    line_num = 0
+    while line_num < len(source):
+        m = _python_block_re.match(source[line_num])
+        if not m or line_num in strings or line_num in continuation_lines:
+            line_num += 1
+            continue
+        leading_spaces, header = m.groups()
+
+        # The block runs until the first non-blank line that is not indented
+        # deeper than its header, not counting lines inside strings. Trailing
+        # blank lines are not part of it.
+        start = end = line_num
+        for line_num in range(start + 1, len(source)):
+            line = source[line_num]
+            if line_num in strings:
+                end = line_num
+                continue
+            if not line.strip():
                 continue
-            new_code += f"{spaces}{line}\n"
-        new_code = re.sub(r"\s+$", "", new_code)
-        reformatted[(line_num, line_num)] = new_code
-
-    is_python = False
-    current_group = None
-    python_block_types = {}
-    python_block_ranges = {}
-    current_group_line_num = 0
-    python_blocks = defaultdict(list)
-    for line_num, line in enumerate(source):
-        m = re.match(r"(\s*)(.*)", line)
-        if not m:
+            if len(line) - len(line.lstrip()) <= len(leading_spaces):
+                break
+            end = line_num
+        line_num = end + 1
+        if end == start:
             continue
-        leading_spaces, code = m.groups()
-        code = code.strip()
-        src_indent = len(leading_spaces)
 
-        if src_indent == 0 and code:
-            python_block_ranges[current_group_line_num] = (
-                current_group_line_num,
-                line_num - 1,
-            )
-            current_group = code.rstrip(":")
-            current_group_line_num = line_num
-            if current_group == "python early":
-                is_python = True
-            elif current_group == "python":
-                is_python = True
-            elif m := re.match(r"init\s+(?:-|\+)?\d+\s+python", current_group):
-                is_python = True
-            else:
-                is_python = False
-            python_block_types[current_group_line_num] = current_group
-
-        if current_group and is_python and line_num != current_group_line_num:
-            python_blocks[current_group_line_num].append(line)
-
-    if current_group_line_num:
-        python_block_ranges[current_group_line_num] = (current_group_line_num, line_num)
-
-    for line_num, block in python_blocks.items():
-        block, margin = dedent("\n".join(block))
-        block_fmt = black.format_str(block, mode=black.FileMode())
-        start, end = python_block_ranges[line_num]
-        reformatted[(start, end)] = f"{python_block_types[line_num]}:\n" + indent(
-            block_fmt, margin
-        )
+        if any(
+            source[n] and not source[n].strip()
+            for n in range(start + 1, end + 1)
+            if n in strings
+        ):
+            # dedent() and indent() would empty whitespace-only lines that are
+            # part of a string.
+            continue
+        block, margin = dedent("\n".join(source[start + 1 : end + 1]))
+        if len(margin or "") <= len(leading_spaces):
+            # A string in the block has lines that are not indented under the
+            # header, so the block cannot be re-indented without changing it.
+            continue
+        if "\t" in margin:
+            # Black indents nested code with spaces, which would leave the block
+            # with mixed indentation. Such blocks are converted by --fix-tabs.
+            continue
+        try:
+            block_fmt = black.format_str(block, mode=black.FileMode())
+        except black.InvalidInput as e:
+            raise format_error(e, block, start + 2, end + 1, len(margin or ""))
+        block_fmt = indent(block_fmt, margin).rstrip("\n")
+        if verify:
+            # Compare the re-indented block against the original lines, both
+            # dedented the same way, so that indent() is checked too.
+            black.assert_equivalent(block, dedent(block_fmt)[0])
+        reformatted[(start, end)] = f"{leading_spaces}{header}:\n{block_fmt}"
 
+    # Regions are spliced in from the bottom up so that the line numbers of
+    # the ones above stay valid. An overlap would drop or move lines.
     code_fmt = copy.deepcopy(source)
-    for (start, end), code in sorted(
-        reformatted.items(), key=lambda x: x[0][0], reverse=True
-    ):
-        if end == -1:
-            code_fmt = [code]
-            continue
+    next_start = len(source)
+    for (start, end), code in sorted(reformatted.items(), reverse=True):
+        assert end < next_start, f"overlapping regions at line {start + 1}"
+        next_start = start
         del code_fmt[start : end + 1]
         code_fmt.insert(start, code)
 
-    code_fmt = "\n".join(code_fmt).strip() + "\n"
+    code_fmt = "\n".join(code_fmt).strip()
+    if final_newline:
+        code_fmt += "\n"
 
-    return code_fmt
+    return code_fmt.replace("\n", newline)
@@ -1,7 +1,226 @@
+import codecs
 import re
 
 _whitespace_only_re = re.compile("^[ \t]+$", re.MULTILINE)
 _leading_whitespace_re = re.compile("(^[ \t]*)(?:[^ \t\n])", re.MULTILINE)
+_line_ending_re = re.compile(r"\r?\n")
+_line_re = re.compile(r"[^\n]*\n|[^\n]+")
+_string_start_re = re.compile(r"\"\"\"|'''|```|[\"'`#]")
+_python_token_re = re.compile(r"\"\"\"|'''|[\"'#()\[\]{}]")
+
+_closing_brackets = {")": "(", "]": "[", "}": "{"}
+
+LINE_ENDINGS = {"lf": "\n", "crlf": "\r\n"}
+
+_boms = [
+    (codecs.BOM_UTF8, "utf-8"),
+    (codecs.BOM_UTF16_LE, "utf-16-le"),
+    (codecs.BOM_UTF16_BE, "utf-16-be"),
+]
+
+
+class DecodeError(Exception):
+    pass
+
+
+def decode_source(data, fallback_encoding=None):
+    """Decode the raw bytes of a script.
+
+    Returns a tuple of the decoded text, the name of the encoding that was
+    used and whether the input started with a UTF-8 byte order mark. Files
+    that are neither valid UTF-8 nor start with a BOM are only decoded with
+    `fallback_encoding` if one is given, since guessing a legacy encoding can
+    silently garble the text; otherwise a `DecodeError` is raised.
+    """
+    for bom, encoding in _boms:
+        if not data.startswith(bom):
+            continue
+        try:
+            return data[len(bom) :].decode(encoding), encoding, bom == codecs.BOM_UTF8
+        except UnicodeDecodeError as e:
+            offset = len(bom) + e.start
+            raise DecodeError(
+                f"input starts with a {encoding.upper()} byte order mark but is not "
+                f"valid {encoding.upper()} (invalid byte 0x{data[offset]:02x} "
+                f"at offset {offset})"
+            )
+
+    try:
+        return data.decode("utf-8"), "utf-8", False
+    except UnicodeDecodeError as e:
+        error = e
+
+    message = (
+        f"input is not valid UTF-8 (invalid byte 0x{data[error.start]:02x} "
+        f"at offset {error.start})"
+    )
+    if fallback_encoding is None:
+        raise DecodeError(f"{message}, pass --encoding to read a legacy encoding")
+    try:
+        return data.decode(fallback_encoding), fallback_encoding, False
+    except UnicodeDecodeError:
+        raise DecodeError(f"{message} and could not be decoded as {fallback_encoding}")
+
+
+def encode_source(text, bom=False):
+    """Encode formatted text as UTF-8, which is the only encoding Ren'Py reads."""
+    data = text.encode("utf-8")
+    return codecs.BOM_UTF8 + data if bom else data
+
+
+def split_lines(text, keepends=False):
+    """Split `text` into lines at LF and CRLF line breaks only.
+
+    Unlike `str.splitlines()`, characters such as U+2028 or form feeds, which
+    can appear inside dialogue, stay part of their line.
+    """
+    lines = _line_re.findall(text)
+    if keepends:
+        return lines
+    return [_line_ending_re.sub("", line) for line in lines]
+
+
+def detect_line_ending(text):
+    """Return the line ending used by the first line break in `text`.
+
+    Files without any line break are treated as using LF.
+    """
+    m = _line_ending_re.search(text)
+    return m.group() if m else "\n"
+
+
+def string_lines(lines):
+    """Return the indices of the lines in `lines` that start inside a string literal.
+
+    Ren'Py strings (and Python's triple-quoted ones) may span several lines.
+    Their continuation lines are text, not statements, so they must never be
+    matched as `$` lines or python block headers, nor have their whitespace
+    touched.
+    """
+    inside = set()
+    delimiter = None
+    for line_num, line in enumerate(lines):
+        if delimiter:
+            inside.add(line_num)
+        pos = 0
+        while pos < len(line):
+            if delimiter is None:
+                m = _string_start_re.search(line, pos)
+                if not m or m.group() == "#":
+                    break
+                delimiter = m.group()
+                pos = m.end()
+            elif line[pos] == "\\":
+                pos += 2
+            elif line.startswith(delimiter, pos):
+                pos += len(delimiter)
+                delimiter = None
+            else:
+                pos += 1
+    return inside
+
+
+def _scan_python(code, position):
+    """Track the brackets and strings of Python `code`.
+
+    Returns a tuple of a message for a closing bracket that does not match,
+    the innermost bracket or string still open at the end as a tuple of its
+    opening token and 1-based line and column, and whether that construct
+    carries on past the end of `code`, which code ending in a backslash
+    outside of a string and comment also does. A single-quoted string that
+    ends a line without a backslash never carries on.
+    """
+    stack = []
+    delimiter = None
+    joined = False
+    for line_num, line in enumerate(split_lines(code), 1):
+        pos = 0
+        comment = False
+        while pos < len(line):
+            if delimiter is None:
+                m = _python_token_re.search(line, pos)
+                if not m or m.group() == "#":
+                    comment = bool(m)
+                    break
+                token = m.group()
+                pos = m.end()
+                if token in _closing_brackets:
+                    at = position(line_num, m.start() + 1)
+                    if not stack:
+                        return f"unmatched '{token}' at {at}", None, False
+                    opener, opener_line, opener_column = stack.pop()
+                    if opener != _closing_brackets[token]:
+                        opened_at = position(opener_line, opener_column)
+                        return (
+                            f"'{token}' at {at} does not match"
+                            f" '{opener}' opened at {opened_at}",
+                            None,
+                            False,
+                        )
+                elif token in "([{":
+                    stack.append((token, line_num, m.start() + 1))
+                else:
+                    delimiter = (token, line_num, m.start() + 1)
+            elif line[pos] == "\\":
+                pos += 2
+            elif line.startswith(delimiter[0], pos):
+                pos += len(delimiter[0])
+                delimiter = None
+            else:
+                pos += 1
+        if delimiter and len(delimiter[0]) == 1 and not line.endswith("\\"):
+            return None, delimiter, False
+        joined = not delimiter and not comment and line.endswith("\\")
+
+    if delimiter:
+        return None, delimiter, True
+    if stack:
+        return None, stack[-1], True
+    return None, None, joined
+
+
+def find_unbalanced(code, position=lambda line, column: f"{line}:{column}"):
+    """Describe the first unbalanced bracket or unterminated string in Python `code`.
+
+    Returns None if every bracket and string is closed. Locations in the
+    description are produced by `position` from 1-based line and column
+    numbers in `code`.
+    """
+    message, opener, _ = _scan_python(code, position)
+    if message or not opener:
+        return message
+    token, line, column = opener
+    if token in "([{":
+        return f"'{token}' opened at {position(line, column)} is never closed"
+    return f"string opened at {position(line, column)} is never closed"
+
+
+def continues_logical_line(code):
+    """Return whether the logical line at the end of Python `code` continues.
+
+    It does inside a bracket or a multi-line string and after a backslash, in
+    which case Ren'Py joins the following lines into it, just like Python.
+    """
+    return _scan_python(code, lambda line, column: None)[2]
+
+
+def expand_leading_tabs(text, tab_width=4):
+    """Expand the tabs in the indentation of every line to spaces.
+
+    Tabs advance to the next multiple of `tab_width` columns, as with
+    `str.expandtabs()`. Returns the new text and the number of lines that
+    were changed. Lines inside multi-line strings are left alone.
+    """
+    lines = split_lines(text, keepends=True)
+    strings = string_lines(lines)
+    changed = 0
+    for line_num, line in enumerate(lines):
+        stripped = line.lstrip(" \t")
+        leading = line[: len(line) - len(stripped)]
+        if "\t" in leading and line_num not in strings:
+            lines[line_num] = leading.expandtabs(tab_width) + stripped
+            changed += 1
+    return "".join(lines), changed
 
 
 def dedent(text):
@@ -68,7 +287,7 @@ def indent(text, prefix, predicate=None):
             return line.strip()
 
     def prefixed_lines():
-        for line in text.splitlines(True):
+        for line in split_lines(text, keepends=True):
             yield (prefix + line if predicate(line) else line)
 
     return "".join(prefixed_lines())
@@ -0,0 +1,242 @@
+"""Tests for the command line interface: exit codes, decoding, output and backups."""
+import os
+import tempfile
+import unittest
+from pathlib import Path
+from unittest import mock
+
+from click.testing import CliRunner
+
+from renpyfmt.cli import cli
+
+UNFORMATTED = b"label start:\n    $ x=1\n"
+FORMATTED = b"label start:\n    $ x = 1\n"
+
+
+class CliTest(unittest.TestCase):
+    def setUp(self):
+        self.runner = CliRunner()
+        self.addCleanup(os.chdir, os.getcwd())
+        tmp = tempfile.TemporaryDirectory()
+        self.addCleanup(tmp.cleanup)
+        self.dir = Path(tmp.name)
+
+    def script(self, data, name="script.rpy"):
+        path = self.dir / name
+        path.write_bytes(data)
+        return path
+
+    def invoke(self, *args, input=None):
+        return self.runner.invoke(cli, [str(arg) for arg in args], input=input)
+
+    def test_stdin_to_stdout(self):
+        result = self.invoke(input=UNFORMATTED)
+        self.assertEqual(result.exit_code, 0, result.output)
+        self.assertEqual(result.stdout_bytes, FORMATTED)
+
+    def test_check_formatted(self):
+        path = self.script(FORMATTED)
+        result = self.invoke("--check", path)
+        self.assertEqual(result.exit_code, 0, result.output)
+        self.assertEqual(result.stdout_bytes, b"")
+
+    def test_check_unformatted(self):
+        path = self.script(UNFORMATTED)
+        result = self.invoke("--check", path, path)
+        self.assertEqual(result.exit_code, 1, result.output)
+        self.assertIn(f"would reformat {path}", result.stderr)
+        self.assertEqual(path.read_bytes(), UNFORMATTED)
+
+    def test_check_legacy_encoding(self):
+        path = self.script('e "こんにちは"\n'.encode("cp932"))
+        result = self.invoke("--check", "--encoding", "cp932", path)
+        self.assertEqual(result.exit_code, 1, result.output)
+
+    def test_check_fix_tabs(self):
+        path = self.script(b"label start:\n\t$ x = 1\n")
+        result = self.invoke("--check", "--fix-tabs", path)
+        self.assertEqual(result.exit_code, 1, result.output)
+
+    def test_parse_error(self):
+        path = self.script(b"label start:\n    $ x = (1, 2]\n")
+        result = self.invoke(path)
+        self.assertEqual(result.exit_code, 2, result.output)
+        self.assertIn(f"{path}:2:16: cannot parse", result.stderr)
+        self.assertEqual(result.stdout_bytes, b"")
+
+    def test_internal_error(self):
+        path = self.script(UNFORMATTED)
+        with mock.patch("renpyfmt.cli.code_format", side_effect=RuntimeError("boom")):
+            result = self.invoke(path, path)
+        self.assertEqual(result.exit_code, 3, result.output)
+        self.assertIn("internal error: RuntimeError('boom')", result.stderr)
+        self.assertEqual(path.read_bytes(), UNFORMATTED)
+
+    def test_error_on_warnings(self):
+        tabs = self.script(b"label start:\n\t$ x = 1\n", name="tabs.rpy")
+        legacy = self.script('e "こんにちは"\n'.encode("cp932"), name="legacy.rpy")
+        for args in [["--fix-tabs", tabs], ["--encoding", "cp932", legacy]]:
+            with self.subTest(args=args):
+                result = self.invoke(*args)
+                self.assertEqual(result.exit_code, 0, result.output)
+
+                result = self.invoke("--error-on", "warnings", *args)
+                self.assertEqual(result.exit_code, 2, result.output)
+                self.assertTrue(result.stdout_bytes)
+
+                result = self.invoke("--error-on", "warnings", "--check", *args)
+                self.assertEqual(result.exit_code, 2, result.output)
+
+    def test_error_on_warnings_without_warnings(self):
+        path = self.script(UNFORMATTED)
+        result = self.invoke("--error-on", "warnings", "--fix-tabs", path)
+        self.assertEqual(result.exit_code, 0, result.output)
+        self.assertEqual(result.stdout_bytes, FORMATTED)
+
+    def test_error_on_never(self):
+        source = b"label start:\n    $ x = (1, 2]\n    $ y=1\n"
+        path = self.script(source)
+        result = self.invoke("--error-on", "never", path)
+        self.assertEqual(result.exit_code, 0, result.output)
+        self.assertIn("Warning: ", result.stderr)
+        self.assertIn(f"{path}:2:16: cannot parse", result.stderr)
+        self.assertEqual(result.stdout_bytes, source)
+
+        result = self.invoke("--error-on", "never", "--check", path)
+        self.assertEqual(result.exit_code, 0, result.output)
+
+        undecodable = self.script(b'e "\xff"\n', name="undecodable.rpy")
+        result = self.invoke("--error-on", "never", undecodable)
+        self.assertEqual(result.exit_code, 0, result.output)
+        self.assertEqual(result.stdout_bytes, b'e "\xff"\n')
+
+    def test_error_on_never_internal_error(self):
+        path = self.script(UNFORMATTED)
+        with mock.patch("renpyfmt.cli.code_format", side_effect=RuntimeError("boom")):
+            result = self.invoke("--error-on", "never", path)
+        self.assertEqual(result.exit_code, 3, result.output)
+
+    def test_unwritable_output(self):
+        path = self.script(UNFORMATTED)
+        result = self.invoke(path, self.dir / "missing" / "out.rpy")
+        self.assertEqual(result.exit_code, 2, result.output)
+        self.assertNotIn("Traceback", result.output)
+        self.assertIn(
+            "out.rpy: cannot write output: No such file or directory", result.stderr
+        )
+
+    def test_unwritable_backup(self):
+        path = self.script(UNFORMATTED)
+        with mock.patch(
+            "shutil.copy2", side_effect=PermissionError(13, "Permission denied")
+        ):
+            result = self.invoke("--backup", path, path)
+        self.assertEqual(result.exit_code, 2, result.output)
+        self.assertIn("cannot write output: Permission denied", result.stderr)
+        self.assertEqual(path.read_bytes(), UNFORMATTED)
+
+    def test_legacy_encoding(self):
+        path = self.script('e "こんにちは"\n'.encode("cp932"))
+        result = self.invoke(path)
+        self.assertEqual(result.exit_code, 2, result.output)
+        self.assertIn("pass --encoding", result.output)
+
+        result = self.invoke("--encoding", "cp932", path)
+        self.assertEqual(result.exit_code, 0, result.output)
+        self.assertEqual(result.stdout_bytes, 'e "こんにちは"\n'.encode("utf-8"))
+        self.assertIn("read as cp932", result.stderr)
+
+    def test_unknown_encoding(self):
+        result = self.invoke("--encoding", "nope", input=UNFORMATTED)
+        self.assertEqual(result.exit_code, 2, result.output)
+        self.assertIn("unknown encoding 'nope'", result.output)
+
+    def test_fix_tabs(self):
+        source = b"label start:\n\tpython:\n\t\tif x:\n\t\t\ty=1\n"
+        path = self.script(source)
+        result = self.invoke(path)
+        self.assertEqual(result.exit_code, 0, result.output)
+        self.assertEqual(result.stdout_bytes, source)
+
+        result = self.invoke("--fix-tabs", "--tab-width", "2", path)
+        self.assertEqual(result.exit_code, 0, result.output)
+        self.assertEqual(
+            result.stdout_bytes,
+            b"label start:\n  python:\n    if x:\n        y = 1\n",
+        )
+        self.assertIn("on 3 line(s)", result.stderr)
+
+    def test_in_place(self):
+        path = self.script(UNFORMATTED)
+        result = self.invoke(path, path)
+        self.assertEqual(result.exit_code, 0, result.output)
+        self.assertEqual(path.read_bytes(), FORMATTED)
+        self.assertFalse(path.with_name("script.rpy.bak").exists())
+
+    def test_backup(self):
+        path = self.script(UNFORMATTED)
+        result = self.invoke("--backup", path, path)
+        self.assertEqual(result.exit_code, 0, result.output)
+        self.assertEqual(path.read_bytes(), FORMATTED)
+        self.assertEqual(path.with_name("script.rpy.bak").read_bytes(), UNFORMATTED)
+
+    def test_backup_is_not_overwritten(self):
+        path = self.script(UNFORMATTED)
+        self.assertEqual(self.invoke("--backup", path, path).exit_code, 0)
+        path.write_bytes(FORMATTED + b"    $ y=2\n")
+        result = self.invoke("--backup", path, path)
+        self.assertEqual(result.exit_code, 0, result.output)
+        self.assertEqual(path.read_bytes(), FORMATTED + b"    $ y = 2\n")
+        self.assertEqual(path.with_name("script.rpy.bak").read_bytes(), UNFORMATTED)
+
+    def test_backup_dir(self):
+        os.chdir(self.dir)
+        (self.dir / "game").mkdir()
+        path = self.script(UNFORMATTED, name="game/script.rpy")
+        result = self.invoke("--backup-dir", "backups", "game/script.rpy", path)
+        self.assertEqual(result.exit_code, 0, result.output)
+        self.assertEqual(path.read_bytes(), FORMATTED)
+        self.assertEqual(
+            (self.dir / "backups" / "game" / "script.rpy").read_bytes(), UNFORMATTED
+        )
+        self.assertFalse(path.with_name("script.rpy.bak").exists())
+
+    def test_backup_dir_outside_current_directory(self):
+        os.chdir(self.dir)
+        outside = tempfile.TemporaryDirectory()
+        self.addCleanup(outside.cleanup)
+        path = Path(outside.name) / "script.rpy"
+        path.write_bytes(UNFORMATTED)
+        result = self.invoke("--backup-dir", "backups", path, path)
+        self.assertEqual(result.exit_code, 0, result.output)
+        backup = self.dir / "backups" / str(path.resolve()).lstrip(os.sep)
+        self.assertEqual(backup.read_bytes(), UNFORMATTED)
+
+    def test_backup_of_separate_output_file(self):
+        source = self.script(UNFORMATTED)
+        target = self.script(b"old\n", name="out.rpy")
+        result = self.invoke("--backup", source, target)
+        self.assertEqual(result.exit_code, 0, result.output)
+        self.assertEqual(target.read_bytes(), FORMATTED)
+        self.assertEqual(target.with_name("out.rpy.bak").read_bytes(), b"old\n")
+
+    def test_no_backup_for_new_output_file(self):
+        source = self.script(UNFORMATTED)
+        target = self.dir / "out.rpy"
+        result = self.invoke("--backup", source, target)
+        self.assertEqual(result.exit_code, 0, result.output)
+        self.assertEqual(target.read_bytes(), FORMATTED)
+        self.assertFalse(target.with_name("out.rpy.bak").exists())
+
+    def test_unchanged_file_is_not_touched(self):
+        path = self.script(FORMATTED)
+        os.utime(path, (0, 0))
+        result = self.invoke("--backup", path, path)
+        self.assertEqual(result.exit_code, 0, result.output)
+        self.assertEqual(path.read_bytes(), FORMATTED)
+        self.assertEqual(path.stat().st_mtime, 0)
+        self.assertFalse(path.with_name("script.rpy.bak").exists())
+
+
+if __name__ == "__main__":
+    unittest.main()
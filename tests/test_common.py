@@ -0,0 +1,98 @@
+"""Unit tests for the helpers in `renpyfmt.common`."""
+import codecs
+import unittest
+
+from renpyfmt.common import (
+    DecodeError,
+    decode_source,
+    encode_source,
+    expand_leading_tabs,
+)
+
+TEXT = 'label start:\n    e "こんにちは"\n'
+
+
+class DecodeSourceTest(unittest.TestCase):
+    def test_utf8(self):
+        self.assertEqual(decode_source(TEXT.encode("utf-8")), (TEXT, "utf-8", False))
+
+    def test_utf8_bom_round_trip(self):
+        data = codecs.BOM_UTF8 + TEXT.encode("utf-8")
+        text, encoding, bom = decode_source(data)
+        self.assertEqual((text, encoding, bom), (TEXT, "utf-8", True))
+        self.assertEqual(encode_source(text, bom=bom), data)
+
+    def test_utf16_bom(self):
+        for bom, codec in [
+            (codecs.BOM_UTF16_LE, "utf-16-le"),
+            (codecs.BOM_UTF16_BE, "utf-16-be"),
+        ]:
+            with self.subTest(codec=codec):
+                text, encoding, bom = decode_source(bom + TEXT.encode(codec))
+                self.assertEqual((text, encoding, bom), (TEXT, codec, False))
+                self.assertEqual(encode_source(text, bom=bom), TEXT.encode("utf-8"))
+
+    def test_legacy_encoding_requires_opt_in(self):
+        with self.assertRaisesRegex(DecodeError, "pass --encoding"):
+            decode_source(TEXT.encode("cp932"))
+
+    def test_cp932(self):
+        self.assertEqual(
+            decode_source(TEXT.encode("cp932"), "cp932"), (TEXT, "cp932", False)
+        )
+
+    def test_utf8_is_preferred_over_fallback(self):
+        self.assertEqual(
+            decode_source(TEXT.encode("utf-8"), "cp932"), (TEXT, "utf-8", False)
+        )
+
+    def test_undecodable(self):
+        data = b'e "\x81\x7f"\n'
+        with self.assertRaisesRegex(
+            DecodeError, "invalid byte 0x81 at offset 3.*could not be decoded as cp932"
+        ):
+            decode_source(data, "cp932")
+
+    def test_invalid_after_utf8_bom(self):
+        with self.assertRaisesRegex(
+            DecodeError,
+            "UTF-8 byte order mark but is not valid UTF-8 "
+            r"\(invalid byte 0xff at offset 6\)",
+        ):
+            decode_source(b'\xef\xbb\xbfe "\xff"\n')
+
+    def test_odd_length_after_utf16_bom(self):
+        with self.assertRaisesRegex(
+            DecodeError,
+            "UTF-16-LE byte order mark but is not valid UTF-16-LE "
+            r"\(invalid byte 0x61 at offset 2\)",
+        ):
+            decode_source(b"\xff\xfea")
+
+
+class ExpandLeadingTabsTest(unittest.TestCase):
+    def test_expands_to_tab_stops(self):
+        self.assertEqual(
+            expand_leading_tabs("\tx\n\t\ty\n  \tz\n", 4),
+            ("    x\n        y\n    z\n", 3),
+        )
+
+    def test_tab_width(self):
+        self.assertEqual(expand_leading_tabs("\tx\n", 2), ("  x\n", 1))
+
+    def test_only_leading_tabs(self):
+        self.assertEqual(
+            expand_leading_tabs('    e "a\tb"\n\tx = 1\t# c\n'),
+            ('    e "a\tb"\n    x = 1\t# c\n', 1),
+        )
+
+    def test_strings_are_left_alone(self):
+        text = 'label start:\n\te """\n\tindented\n\t"""\n'
+        self.assertEqual(
+            expand_leading_tabs(text),
+            ('label start:\n    e """\n\tindented\n\t"""\n', 1),
+        )
+
+
+if __name__ == "__main__":
+    unittest.main()
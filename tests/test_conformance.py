@@ -0,0 +1,89 @@
+"""Differential test against a Ren'Py SDK.
+
+Formats every script of a real project into a scratch copy and runs Ren'Py's
+lint on it, which compiles all scripts. Only runs when `RENPY_SDK` points to
+an SDK directory and `RENPYFMT_PROJECT` to a project (the directory that
+contains `game/`).
+"""
+import os
+import shutil
+import subprocess
+import tempfile
+import unittest
+from pathlib import Path
+
+from renpyfmt.code_format import FormatError, code_format
+
+RENPY_SDK = os.environ.get("RENPY_SDK")
+RENPYFMT_PROJECT = os.environ.get("RENPYFMT_PROJECT")
+
+
+def lint(sdk, project):
+    result = subprocess.run(
+        [str(Path(sdk) / "renpy.sh"), str(project), "lint"],
+        capture_output=True,
+        text=True,
+    )
+    return result.returncode == 0, result.stdout + result.stderr
+
+
+def format_script(path):
+    with path.open(encoding="utf-8", newline="") as f:
+        source = f.read()
+    formatted = code_format(source)
+    if formatted == source:
+        return False
+    with path.open("w", encoding="utf-8", newline="") as f:
+        f.write(formatted)
+    return True
+
+
+@unittest.skipUnless(
+    RENPY_SDK and RENPYFMT_PROJECT, "RENPY_SDK and RENPYFMT_PROJECT are not set"
+)
+class ConformanceTest(unittest.TestCase):
+    def test_formatted_project_compiles(self):
+        project = Path(RENPYFMT_PROJECT)
+        ok, output = lint(RENPY_SDK, project)
+        if not ok:
+            self.skipTest(f"unformatted project does not pass lint:\n{output}")
+
+        with tempfile.TemporaryDirectory() as tmp:
+            formatted_project = Path(tmp) / project.name
+            shutil.copytree(project, formatted_project)
+            # A script that cannot be formatted is left as it is and reported
+            # with the others, rather than aborting the run.
+            changed, errors = [], []
+            for path in sorted((formatted_project / "game").rglob("*.rpy")):
+                script = path.relative_to(formatted_project)
+                try:
+                    if format_script(path):
+                        changed.append(script)
+                except (FormatError, UnicodeDecodeError) as e:
+                    errors.append(f"{script}: {e}")
+            failures = []
+            if errors:
+                failures.append("could not format:\n" + "\n".join(errors))
+
+            ok, output = lint(RENPY_SDK, formatted_project)
+            if not ok:
+                # Narrow the failure down to the files whose formatting broke
+                # compilation by formatting them one at a time.
+                broken = []
+                for script in changed:
+                    shutil.rmtree(formatted_project)
+                    shutil.copytree(project, formatted_project)
+                    format_script(formatted_project / script)
+                    if not lint(RENPY_SDK, formatted_project)[0]:
+                        broken.append(str(script))
+                failures.append(
+                    "formatting broke compilation of: "
+                    f"{', '.join(broken) or '<combination of files>'}\n{output}"
+                )
+
+            if failures:
+                self.fail("\n".join(failures))
+
+
+if __name__ == "__main__":
+    unittest.main()
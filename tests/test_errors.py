@@ -0,0 +1,117 @@
+"""Tests for the positions reported for Python code that cannot be parsed.
+
+Lines and columns are 1-based and refer to the script, not to the extracted
+code, so that editors can jump straight to them.
+"""
+import unittest
+
+from renpyfmt.code_format import FormatError, code_format
+from renpyfmt.common import continues_logical_line, find_unbalanced
+
+
+class FindUnbalancedTest(unittest.TestCase):
+    def test_balanced(self):
+        self.assertIsNone(find_unbalanced('x = f(1, [2], {"(": 3})  # )'))
+        self.assertIsNone(find_unbalanced('x = (\n    """\n)\n"""\n)'))
+
+    def test_unmatched_closer(self):
+        self.assertEqual(find_unbalanced("x = 1)"), "unmatched ')' at 1:6")
+
+    def test_mismatched_closer(self):
+        self.assertEqual(
+            find_unbalanced("x = [\n    (1, 2]\n]"),
+            "']' at 2:10 does not match '(' opened at 2:5",
+        )
+
+    def test_unclosed_bracket(self):
+        self.assertEqual(
+            find_unbalanced("x = {\n    'a': [1],\n"),
+            "'{' opened at 1:5 is never closed",
+        )
+
+    def test_unterminated_string(self):
+        self.assertEqual(
+            find_unbalanced('x = ("abc)\ny = 1'),
+            "string opened at 1:6 is never closed",
+        )
+        self.assertEqual(
+            find_unbalanced("x = '''abc\ny = 1"),
+            "string opened at 1:5 is never closed",
+        )
+
+    def test_position(self):
+        self.assertEqual(
+            find_unbalanced("x = (1]", lambda line, column: f"{line + 9}:{column}"),
+            "']' at 10:7 does not match '(' opened at 10:5",
+        )
+
+
+class ContinuesLogicalLineTest(unittest.TestCase):
+    def test_continues(self):
+        for code in ["x = f(", "x = [1,\n2", 'x = """abc', 'x = "abc\\', "x = 1 + \\"]:
+            with self.subTest(code=code):
+                self.assertTrue(continues_logical_line(code))
+
+    def test_ends(self):
+        for code in [
+            "x = f()",
+            "x = 1  # (",
+            "x = 1  # \\",
+            "x = \\\n1",
+            'x = "abc',
+            "x = 1)",
+            "x = (1]",
+        ]:
+            with self.subTest(code=code):
+                self.assertFalse(continues_logical_line(code))
+
+
+class FormatErrorTest(unittest.TestCase):
+    def assertFormatError(self, source, line, column, message):
+        with self.assertRaises(FormatError) as cm:
+            code_format(source)
+        self.assertEqual(
+            (cm.exception.line, cm.exception.column, cm.exception.message),
+            (line, column, message),
+        )
+
+    def test_dollar_mismatched_closer(self):
+        self.assertFormatError(
+            "label start:\n    $ x = (1, 2]\n    return\n",
+            2,
+            16,
+            "x = (1, 2] (']' at 2:16 does not match '(' opened at 2:11)",
+        )
+
+    def test_dollar_continuation_line(self):
+        self.assertFormatError(
+            "label start:\n    $ x = f(\n        1 2)\n", 3, 11, "        1 2)"
+        )
+
+    def test_dollar_pinned_to_last_line(self):
+        self.assertFormatError(
+            "label start:\n    $ x = f(\n        1,\n",
+            3,
+            1,
+            "EOF in multi-line statement ('(' opened at 2:12 is never closed)",
+        )
+
+    def test_block_after_blank_line(self):
+        self.assertFormatError(
+            "label start:\n    python:\n\n        x = {1: (2]}\n    return\n",
+            4,
+            19,
+            "x = {1: (2]} (']' at 4:19 does not match '(' opened at 4:17)",
+        )
+
+    def test_block_pinned_to_last_line(self):
+        self.assertFormatError(
+            "init python:\n    x = [\n        1,\n",
+            3,
+            5,
+            "EOF in multi-line statement ('[' opened at 2:9 is never closed)",
+        )
+
+
+if __name__ == "__main__":
+    unittest.main()
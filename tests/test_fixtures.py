@@ -0,0 +1,63 @@
+"""Snapshot tests over the fixture corpus.
+
+Every directory in `tests/fixtures` holds an `input.rpy` and the expected
+`output.rpy`, plus an optional `options.json` with keyword arguments for
+`code_format`. Each fixture is formatted and compared against its snapshot,
+then formatted again to check that the output is stable. A missing snapshot
+fails the test; set `RENPYFMT_UPDATE_SNAPSHOTS=1` to write missing or outdated
+snapshots.
+"""
+import json
+import os
+import unittest
+from pathlib import Path
+
+from renpyfmt.code_format import code_format
+
+FIXTURES_DIR = Path(__file__).parent / "fixtures"
+UPDATE_SNAPSHOTS = os.environ.get("RENPYFMT_UPDATE_SNAPSHOTS") == "1"
+
+
+def read_text(path):
+    with path.open(encoding="utf-8", newline="") as f:
+        return f.read()
+
+
+def write_text(path, text):
+    with path.open("w", encoding="utf-8", newline="") as f:
+        f.write(text)
+
+
+class FixtureTest(unittest.TestCase):
+    def test_fixtures(self):
+        fixtures = sorted(path for path in FIXTURES_DIR.iterdir() if path.is_dir())
+        self.assertTrue(fixtures, "no fixtures found")
+
+        for fixture in fixtures:
+            with self.subTest(fixture=fixture.name):
+                options_file = fixture / "options.json"
+                options = (
+                    json.loads(read_text(options_file)) if options_file.exists() else {}
+                )
+                formatted = code_format(
+                    read_text(fixture / "input.rpy"), verify=True, **options
+                )
+
+                snapshot = fixture / "output.rpy"
+                if UPDATE_SNAPSHOTS:
+                    write_text(snapshot, formatted)
+                self.assertTrue(
+                    snapshot.exists(),
+                    "missing snapshot, run with RENPYFMT_UPDATE_SNAPSHOTS=1",
+                )
+
+                self.assertEqual(formatted, read_text(snapshot))
+                self.assertEqual(
+                    code_format(formatted, **options),
+                    formatted,
+                    "formatting is not idempotent",
+                )
+
+
+if __name__ == "__main__":
+    unittest.main()
@@ -0,0 +1,134 @@
+"""Regression tests for the guarantee that formatting never reorders anything.
+
+Ren'Py runs init blocks by priority and then in file order, and statements
+in script order, so moving a statement or a Python statement changes what
+the game does. The formatter only ever rewrites a region in place.
+"""
+import ast
+import textwrap
+import unittest
+from unittest import mock
+
+from renpyfmt.code_format import code_format
+
+
+def python_statements(block):
+    return [ast.dump(node) for node in ast.parse(textwrap.dedent(block)).body]
+
+
+class OrderingTest(unittest.TestCase):
+    def test_init_blocks_keep_file_order(self):
+        source = textwrap.dedent(
+            """\
+            init 10 python:
+                a=1
+            python early:
+                b=2
+            init -10 python:
+                c=3
+            init python:
+                d=4
+            define e = 5
+            init -999 python:
+                f=6
+            """
+        )
+        formatted = code_format(source, verify=True)
+        self.assertEqual(
+            [line for line in formatted.splitlines() if not line.startswith(" ")],
+            [line for line in source.splitlines() if not line.startswith(" ")],
+        )
+        self.assertEqual(
+            [line.strip() for line in formatted.splitlines() if line.startswith(" ")],
+            ["a = 1", "b = 2", "c = 3", "d = 4", "f = 6"],
+        )
+
+    def test_python_statements_keep_order(self):
+        source = (
+            "init python:\n"
+            "    z=1\n"
+            "    import os\n"
+            "    def f(): pass\n"
+            "    a=2\n"
+            "    from sys import path\n"
+        )
+        formatted = code_format(source, verify=True)
+        self.assertEqual(
+            python_statements(formatted.split("\n", 1)[1]),
+            python_statements(source.split("\n", 1)[1]),
+        )
+
+    def test_dollar_statements_keep_order(self):
+        source = (
+            "label start:\n"
+            "    $ b=1; a=2\n"
+            '    e "Hi."\n'
+            "    $ z=3\n"
+            "    $ y=4;x=5\n"
+            "    return\n"
+        )
+        for split_dollar_lines in [False, True]:
+            with self.subTest(split_dollar_lines=split_dollar_lines):
+                formatted = code_format(
+                    source, split_dollar_lines=split_dollar_lines, verify=True
+                )
+                self.assertEqual(
+                    [
+                        part.strip(" $")
+                        for line in formatted.splitlines()
+                        for part in line.split(";")
+                    ],
+                    [
+                        "label start:",
+                        "b = 1",
+                        "a = 2",
+                        'e "Hi."',
+                        "z = 3",
+                        "y = 4",
+                        "x = 5",
+                        "return",
+                    ],
+                )
+
+    def test_script_lines_between_regions_are_untouched(self):
+        source = textwrap.dedent(
+            """\
+            label start:
+                $ a=1
+                scene bg
+                python:
+                    b=2
+                show eileen
+                $ c=3
+                python hide:
+                    d=4
+                return
+            """
+        )
+        formatted = code_format(source, verify=True)
+        script = [
+            "label start:",
+            "    scene bg",
+            "    python:",
+            "    show eileen",
+            "    python hide:",
+            "    return",
+        ]
+        self.assertEqual(
+            [line for line in formatted.splitlines() if line in script], script
+        )
+
+    def test_verify_checks_the_reindented_block(self):
+        source = "init python:\n    a=1\n    b=2\n"
+
+        def swap_lines(text, prefix):
+            return "".join(prefix + line for line in reversed(text.splitlines(True)))
+
+        with mock.patch("renpyfmt.code_format.indent", swap_lines):
+            code_format(source)
+            with self.assertRaises(AssertionError):
+                code_format(source, verify=True)
+
+
+if __name__ == "__main__":
+    unittest.main()
@@ -0,0 +1,255 @@
+"""Randomized round-trip tests.
+
+Generates random scripts mixing say, menu, if and label statements with `$`
+lines and python blocks, including python blocks nested in labels, `$` lines
+with semicolons or spanning several lines, and multi-line strings. Each
+script is formatted, and the test checks that the Python code is unchanged
+(compared by AST), that every other line is kept as written and that
+formatting is idempotent. The generator is seeded, so failures are
+reproducible; set `RENPYFMT_ROUNDTRIP_SEED` to explore other scripts.
+"""
+import ast
+import codeop
+import os
+import random
+import re
+import unittest
+
+from renpyfmt.code_format import code_format
+
+SEED = int(os.environ.get("RENPYFMT_ROUNDTRIP_SEED", "0"))
+SCRIPTS = 200
+
+# Written independently of the formatter's patterns, so that a header or `$`
+# line it misses doesn't go unnoticed here as well.
+_block_header_re = re.compile(
+    r"(\s*)(?:init(?:\s+-?\d+)?\s+)?python(?:\s+early)?(?:\s+hide)?(?:\s+in\s+\w+)?:$"
+)
+_dollar_re = re.compile(r"\s+\$\s*(.*)")
+
+NAMES = ["x", "y", "score", "persistent.seen", "store.flag"]
+SPEAKERS = ["e", "m", "narrator"]
+
+
+def space(rng):
+    return rng.choice(["", " ", "  "])
+
+
+def expression(rng, depth=0):
+    kind = rng.randrange(6 if depth < 2 else 3)
+    if kind == 0:
+        return rng.choice(NAMES)
+    if kind == 1:
+        return str(rng.randrange(100))
+    if kind == 2:
+        return rng.choice(["'a'", '"b"', "'it''s'", "True", "None"])
+    if kind == 3:
+        op = rng.choice(["+", "-", "*", "==", " and ", " or "])
+        left, right = expression(rng, depth + 1), expression(rng, depth + 1)
+        return f"{left}{space(rng)}{op}{space(rng)}{right}"
+    if kind == 4:
+        args = f",{space(rng)}".join(
+            expression(rng, depth + 1) for _ in range(rng.randrange(3))
+        )
+        return f"renpy.pause({space(rng)}{args}{space(rng)})"
+    items = [
+        f"{expression(rng, depth + 1)}:{space(rng)}{expression(rng, depth + 1)}"
+        for _ in range(rng.randrange(3))
+    ]
+    return "{" + ",".join(items) + "}"
+
+
+def python_statement(rng):
+    kind = rng.randrange(3)
+    if kind == 0:
+        return f"{rng.choice(NAMES)}{space(rng)}={space(rng)}{expression(rng)}"
+    if kind == 1:
+        return f"{rng.choice(NAMES)}{space(rng)}+={space(rng)}{expression(rng)}"
+    return expression(rng)
+
+
+def multiline_string(rng, indent):
+    """Return the lines of a triple-quoted string starting on the current line.
+
+    The lines inside it look like script and may be blank or hold nothing but
+    spaces, none of which may be touched.
+    """
+    lines = [f'"""{rng.choice(["", "Hello,"])}']
+    for _ in range(rng.randint(1, 3)):
+        lines.append(
+            rng.choice(
+                [
+                    f"{indent}world {rng.randrange(100)}",
+                    f"{indent}    $ not_code=1",
+                    f"{indent}python:",
+                    "",
+                    f"{indent}  ",
+                ]
+            )
+        )
+    if lines[-1].strip():
+        lines[-1] += '"""'
+    else:
+        lines.append(f'{indent}"""')
+    return lines
+
+
+def python_block(rng, indent=""):
+    header = rng.choice(
+        [
+            "python",
+            "python early",
+            "python hide",
+            "init python",
+            "init python in mystore",
+            f"init {rng.randrange(-5, 5)} python",
+        ]
+    )
+    body = indent + "    "
+    lines = [f"{indent}{header}:"]
+    for _ in range(rng.randint(1, 4)):
+        kind = rng.random()
+        if kind < 0.2:
+            lines.append(f"{body}def  f_{rng.randrange(100)}( a ):")
+            lines.append(f"{body}    return {expression(rng)}")
+        elif kind < 0.3:
+            string = multiline_string(rng, body)
+            lines.append(f"{body}{rng.choice(NAMES)} ={space(rng)}{string[0]}")
+            lines.extend(string[1:])
+        else:
+            lines.append(f"{body}{python_statement(rng)}")
+    return lines
+
+
+def dollar_statement(rng, indent):
+    """Return the lines of a `$` statement, which may span several lines."""
+    name = rng.choice(NAMES)
+    kind = rng.randrange(5)
+    if kind == 0:
+        return [
+            f"{indent}${space(rng)}{python_statement(rng)};{space(rng)}"
+            f"{python_statement(rng)}"
+        ]
+    if kind == 1:
+        return [
+            f"{indent}$ {name} = renpy.pause(",
+            f"{indent}        {expression(rng)},",
+            f"{indent}    {expression(rng)})",
+        ]
+    if kind == 2:
+        return [
+            f"{indent}$ {name} = {expression(rng)} + \\",
+            f"{indent}      {expression(rng)}",
+        ]
+    if kind == 3:
+        string = multiline_string(rng, indent + "  ")
+        return [f"{indent}$ {name} = {string[0]}"] + string[1:]
+    return [f"{indent}${space(rng)}{python_statement(rng)}"]
+
+
+def script_block(rng, indent, depth=0):
+    lines = []
+    for _ in range(rng.randint(1, 5)):
+        if rng.random() < 0.2:
+            lines.append("")
+        kind = rng.randrange(5 if depth < 2 else 3)
+        if kind == 0:
+            lines.append(
+                f"{indent}{rng.choice(SPEAKERS)} "
+                f'"Line {rng.randrange(1000)}, costs $5."'
+            )
+        elif kind == 1:
+            lines.extend(dollar_statement(rng, indent))
+        elif kind == 2:
+            lines.extend(python_block(rng, indent))
+        elif kind == 3:
+            lines.append(f"{indent}if {expression(rng)}:")
+            lines.extend(script_block(rng, indent + "    ", depth + 1))
+        else:
+            lines.append(f"{indent}menu:")
+            for choice in range(rng.randint(1, 3)):
+                lines.append(f'{indent}    "Choice {choice}":')
+                lines.extend(script_block(rng, indent + "        ", depth + 1))
+    return lines
+
+
+def script(rng):
+    lines = []
+    for i in range(rng.randint(1, 6)):
+        if rng.random() < 0.4:
+            lines.extend(python_block(rng))
+        else:
+            lines.append(f"label l{i}:")
+            lines.extend(script_block(rng, "    "))
+        lines.append("")
+    return "\n".join(lines)
+
+
+def indentation(line):
+    return len(line) - len(line.lstrip())
+
+
+def statements(lines):
+    """Return the ASTs of the statements in Python code of the given lines.
+
+    The indentation of the first non-blank line is removed from every line
+    that has it, which leaves the lines inside strings as they are.
+    """
+    margin = next(line[: indentation(line)] for line in lines if line.strip())
+    code = "\n".join(
+        line[len(margin) :] if line.startswith(margin) else line for line in lines
+    )
+    return [ast.dump(node) for node in ast.parse(code).body]
+
+
+def incomplete(code):
+    return codeop.compile_command(code, symbol="exec") is None
+
+
+def split(text):
+    """Split a script into the ASTs of its Python statements and its other lines."""
+    code, other = [], []
+    lines = text.split("\n")
+    line_num = 0
+    while line_num < len(lines):
+        line = lines[line_num]
+        line_num += 1
+        if m := _block_header_re.match(line):
+            # The block ends at the first non-blank line that is not indented
+            # deeper than its header.
+            other.append(line)
+            start = line_num
+            while line_num < len(lines) and (
+                not lines[line_num].strip()
+                or indentation(lines[line_num]) > len(m.group(1))
+            ):
+                line_num += 1
+            code.extend(statements(lines[start:line_num]))
+        elif m := _dollar_re.match(line):
+            logical_line = [m.group(1)]
+            while incomplete("\n".join(logical_line)) and line_num < len(lines):
+                logical_line.append(lines[line_num])
+                line_num += 1
+            code.extend(statements(logical_line))
+        elif line.strip():
+            other.append(line)
+    return code, other
+
+
+class RoundTripTest(unittest.TestCase):
+    def test_roundtrip(self):
+        rng = random.Random(SEED)
+        for i in range(SCRIPTS):
+            source = script(rng)
+            with self.subTest(script=i, source=source):
+                split_dollar_lines = rng.random() < 0.5
+                formatted = code_format(source, split_dollar_lines=split_dollar_lines)
+                self.assertEqual(split(formatted), split(source))
+                self.assertEqual(
+                    code_format(formatted, split_dollar_lines=split_dollar_lines),
+                    formatted,
+                )
+
+
+if __name__ == "__main__":
+    unittest.main()